@@ -1,21 +1,26 @@
+use std::collections::VecDeque;
+use std::str::Chars;
 use anyhow::{Result, bail};
 use crate::arch::{Register, Register8, Register16};
 
 /// Represents position of a character in code
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct CodePosition {
+  /// Byte offset into the source, used for slicing UTF-8 correctly
+  pub byte: usize,
   pub char: usize,
   pub row: usize,
   pub col: usize,
 }
 impl CodePosition {
   /// Create a new [`CodePosition`]
-  pub fn new(char: usize, row: usize, col: usize) -> Self {
-    Self { char, row, col }
+  pub fn new(byte: usize, char: usize, row: usize, col: usize) -> Self {
+    Self { byte, char, row, col }
   }
-  /// Creates a new [`CodePosition`] pointing to the next character
-  pub fn next(&self) -> Self {
+  /// Creates a new [`CodePosition`] pointing to the character after `chr`
+  pub fn next(&self, chr: char) -> Self {
     Self {
+      byte: self.byte + chr.len_utf8(),
       char: self.char + 1,
       col: self.col + 1,
       ..*self
@@ -24,6 +29,7 @@ impl CodePosition {
   /// Creates a new [`CodePosition`] pointing to the start of the next row
   pub fn next_row(&self) -> Self {
     Self {
+      byte: self.byte + 1,
       char: self.char + 1,
       col: 0,
       row: self.row + 1
@@ -33,26 +39,62 @@ impl CodePosition {
   pub fn next_auto(&self, chr: char) -> Self {
     match chr {
       '\n' => self.next_row(),
-      _ => self.next()
+      _ => self.next(chr)
     }
   }
 }
 impl Default for CodePosition {
   fn default() -> Self {
-    Self::new(0, 0, 0)
+    Self::new(0, 0, 0, 0)
   }
 }
 
+/// Classifies a malformed token produced by the recovering lexer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LexErrorKind {
+  /// A numeric literal with no digits, a misplaced `_` separator, an
+  /// out-of-range value, or a malformed float (e.g. an exponent with no digits)
+  MalformedInteger,
+  /// An unknown or truncated escape sequence inside a string or char literal
+  InvalidEscape,
+  /// A `\x`/`\u{...}` escape with missing or non-hex digits
+  InvalidHexEscape,
+  /// A `\x`/`\u{...}` escape whose value is not a valid `char`
+  InvalidEscapeValue,
+  /// An empty (`''`), multi-character, or unterminated character literal
+  MalformedChar,
+  /// A string literal that reached EOF before its closing quote
+  UnterminatedString,
+  /// A block comment that reached EOF before its closing `*/`
+  UnterminatedBlockComment,
+  /// A character that doesn't start any known token
+  UnexpectedChar,
+}
+
+/// Whether a comment spans to the end of the line or is delimited
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommentShape {
+  /// `;` or `//` to the end of the line
+  Line,
+  /// `/* ... */`, possibly nested
+  Block,
+}
+
 #[derive(Clone, Debug)]
 pub enum TokenType {
   InstructionOrKeyword(String),
   StringLiteral(String),
-  // CharLiteral(char),
+  CharLiteral(char),
   IntegerLiteral(isize),
+  FloatLiteral(f64),
   SymbolLiteral(String),
   Symbol(String),
   RegisterPointer(Register),
   Whitespace,
+  /// A line or block comment, carrying its raw body text
+  Comment { shape: CommentShape, text: String },
+  /// A malformed token, emitted only by [`Tokenizer::tokenize_lossy`]
+  Error { kind: LexErrorKind, text: String },
   Eof,
 }
 
@@ -65,18 +107,24 @@ pub struct Token {
 #[derive(Clone)]
 pub struct Tokenizer<'a> {
   code: &'a str,
+  /// Remaining, not-yet-consumed tail of `code`; kept in sync with `position.byte`
+  chars: Chars<'a>,
   tokens: Vec<Token>,
-  position: CodePosition
+  position: CodePosition,
+  /// When set, malformed tokens become [`TokenType::Error`] instead of bailing
+  recover: bool,
 }
 impl<'a> Tokenizer<'a> {
   /// Creates a new [`Tokenizer`]
-  /// 
+  ///
   /// Please note that in most cases `Tokenizer::tokenize`  should be used instead!
   pub fn new(code: &'a str) -> Self {
     Self {
       code,
+      chars: code.chars(),
       tokens: Vec::new(),
-      position: CodePosition::default()
+      position: CodePosition::default(),
+      recover: false,
     }
   }
 
@@ -102,27 +150,122 @@ impl<'a> Tokenizer<'a> {
     Ok(tokenizer.finish())
   }
 
-  fn peek(&self, offset: isize) -> Option<char> {
-    self.code.chars().nth(self.position.char.wrapping_add_signed(offset))
+  /// Tokenize `code` without ever bailing out.
+  ///
+  /// Unlike [`Tokenizer::tokenize`], a malformed token does not abort the pass:
+  /// it is emitted as a [`TokenType::Error`] spanning the offending text, the
+  /// lexer resynchronizes at the next whitespace boundary, and lexing continues.
+  /// A single pass therefore surfaces *every* lexical problem with its position.
+  pub fn tokenize_lossy(code: &'a str) -> Vec<Token> {
+    let mut tokenizer = Self::new(code);
+    tokenizer.recover = true;
+    // In recovery mode `step` never returns `Err`, so unwrapping is sound.
+    while !tokenizer.step().expect("recovering tokenizer must not fail") {}
+    tokenizer.finish()
   }
-  fn peek_range(&self, offset: isize, len: usize) -> Option<&str> {
-    let start = self.position.char.wrapping_add_signed(offset);
-    if start + len > self.code.len() {
-      return None
-    }
-    Some(&self.code[start..(start+len)])
+
+  /// Look ahead `offset` characters without consuming anything.
+  ///
+  /// Cheap: cloning a [`Chars`] just copies a `&str` slice pointer.
+  fn peek(&self, offset: usize) -> Option<char> {
+    self.chars.clone().nth(offset)
   }
   fn take(&mut self) -> Option<char> {
-    let chr = self.peek(0)?;
+    let chr = self.chars.next()?;
     self.position = self.position.next_auto(chr);
     Some(chr)
   }
 
+  /// Emit a [`TokenType::Error`] token and resynchronize after a malformed token.
+  ///
+  /// Consumes up to the next whitespace boundary so the following token starts
+  /// clean, then records everything from `start` onwards as the error's text.
+  fn recover_error(&mut self, kind: LexErrorKind, start: CodePosition) {
+    while let Some(chr) = self.peek(0) {
+      if chr.is_whitespace() { break }
+      self.take().unwrap();
+    }
+    // Guarantee forward progress even if nothing could be consumed
+    if self.position.byte == start.byte {
+      self.take();
+    }
+    let text = self.code[start.byte..self.position.byte].to_string();
+    self.tokens.push(Token {
+      token: TokenType::Error { kind, text },
+      position: start,
+    });
+  }
+
+  /// Decode one escape sequence, assuming the leading `\` has already been consumed.
+  ///
+  /// Shared by the string and character-literal lexers so both accept exactly
+  /// the same escapes. On failure it returns the [`LexErrorKind`] together with
+  /// a message for the caller's `err!` to surface at the current [`CodePosition`].
+  fn decode_escape(&mut self) -> std::result::Result<char, (LexErrorKind, String)> {
+    match self.take() {
+      Some('n') => Ok('\n'),
+      Some('r') => Ok('\r'),
+      Some('t') => Ok('\t'),
+      Some('0') => Ok('\0'),
+      Some('\\') => Ok('\\'),
+      Some('"') => Ok('"'),
+      Some('\'') => Ok('\''),
+      Some('x') => {
+        // Exactly two hex digits
+        let mut value: u32 = 0;
+        for _ in 0..2 {
+          match self.take() {
+            Some(chr) => match chr.to_digit(16) {
+              Some(d) => value = value * 16 + d,
+              None => return Err((LexErrorKind::InvalidHexEscape, format!("Invalid hex escape: expected hex digit, found {:?}", chr))),
+            },
+            None => return Err((LexErrorKind::InvalidHexEscape, "Malformed hex escape: EOF reached".to_string())),
+          }
+        }
+        char::from_u32(value).ok_or_else(|| (LexErrorKind::InvalidEscapeValue, format!("Invalid escape value: \\x{:02X} is not a valid character", value)))
+      }
+      Some('u') => {
+        // `\u{...}` with 1-6 hex digits
+        if self.take() != Some('{') {
+          return Err((LexErrorKind::InvalidHexEscape, "Malformed unicode escape: expected `{`".to_string()));
+        }
+        let mut value: u32 = 0;
+        let mut digits = 0;
+        loop {
+          match self.take() {
+            Some('}') => break,
+            Some(chr) => match chr.to_digit(16) {
+              Some(d) => {
+                digits += 1;
+                if digits > 6 {
+                  return Err((LexErrorKind::InvalidHexEscape, "Malformed unicode escape: too many digits (max 6)".to_string()));
+                }
+                value = value * 16 + d;
+              }
+              None => return Err((LexErrorKind::InvalidHexEscape, format!("Invalid unicode escape: expected hex digit or `}}`, found {:?}", chr))),
+            },
+            None => return Err((LexErrorKind::InvalidHexEscape, "Malformed unicode escape: EOF reached".to_string())),
+          }
+        }
+        if digits == 0 {
+          return Err((LexErrorKind::InvalidHexEscape, "Malformed unicode escape: no digits".to_string()));
+        }
+        char::from_u32(value).ok_or_else(|| (LexErrorKind::InvalidEscapeValue, format!("Invalid escape value: \\u{{{:X}}} is not a valid character", value)))
+      }
+      Some(x) => Err((LexErrorKind::InvalidEscape, format!("Invalid escape sequence: \\{}", x))),
+      None => Err((LexErrorKind::InvalidEscape, "Malformed escape sequence: EOF reached".to_string())),
+    }
+  }
+
   /// Compute at most one token
   /// Returns true if EOF
   pub fn step(&mut self) -> Result<bool> {
     macro_rules! err {
-      ($message: expr) => {{
+      ($kind: expr, $start: expr, $message: expr) => {{
+        if self.recover {
+          self.recover_error($kind, $start);
+          return Ok(false);
+        }
         bail!("Error on line {}, column {}\t||\t{}", self.position.row + 1, self.position.col + 1, $message);
       }};
     }
@@ -153,7 +296,7 @@ impl<'a> Tokenizer<'a> {
     }
 
 
-    //INTEGER TOKEN
+    //NUMBER TOKEN
 
 
     if chr.is_ascii_digit() {
@@ -166,35 +309,102 @@ impl<'a> Tokenizer<'a> {
         }
       } else { 10 };
 
-      if radix != 10 {
-        self.take().unwrap();
-        self.take().unwrap();
-        match self.peek(0) {
-          Some(x) => {
-            if !x.is_digit(radix) {
-              err!("Malformed integer: No integer body")
+      // Scan a run of `radix` digits into `$out`, allowing `_` separators but
+      // only ever *between* two digits (no leading, trailing, or doubled `_`).
+      // Evaluates to the number of digits (separators excluded) consumed.
+      macro_rules! scan_digits {
+        ($out: expr) => {{
+          let mut count = 0usize;
+          let mut after_sep = false;
+          loop {
+            match self.peek(0) {
+              Some('_') => {
+                if count == 0 || after_sep {
+                  err!(LexErrorKind::MalformedInteger, start_pos, "Malformed number: misplaced digit separator")
+                }
+                after_sep = true;
+                self.take().unwrap();
+              }
+              Some(x) if x.is_digit(radix) => {
+                $out.push(x);
+                count += 1;
+                after_sep = false;
+                self.take().unwrap();
+              }
+              _ => {
+                if after_sep {
+                  err!(LexErrorKind::MalformedInteger, start_pos, "Malformed number: trailing digit separator")
+                }
+                break count;
+              }
             }
           }
-          None => err!("Malformed integer: EOF before integer body")
+        }};
+      }
+
+      // Cleaned literal text (separators stripped) re-parsed at the end
+      let mut text = String::new();
+
+      if radix != 10 {
+        self.take().unwrap(); // '0'
+        self.take().unwrap(); // radix marker
+        if scan_digits!(text) == 0 {
+          err!(LexErrorKind::MalformedInteger, start_pos, "Malformed integer: No integer body")
         }
+        let value = match isize::from_str_radix(&text, radix) {
+          Ok(x) => x,
+          Err(_) => err!(LexErrorKind::MalformedInteger, start_pos, "Malformed integer: value out of range"),
+        };
+        self.tokens.push(Token {
+          token: TokenType::IntegerLiteral(value),
+          position: start_pos,
+        });
+        return Ok(false);
       }
 
-      let mut value: isize = 0;
-      while let Some(chr) = self.peek(0) {
-        match chr.to_digit(radix) {
-          Some(x) => {
-            value *= radix as isize;
-            value += x as isize;
-            self.take().unwrap();
-          }
-          None => break
+      // Decimal: an integer body, plus an optional fractional part and/or
+      // exponent that promote the literal to a float.
+      scan_digits!(text);
+      let mut is_float = false;
+
+      if self.peek(0) == Some('.') && self.peek(1).is_some_and(|x| x.is_ascii_digit()) {
+        is_float = true;
+        text.push('.');
+        self.take().unwrap();
+        scan_digits!(text);
+      }
+
+      if matches!(self.peek(0), Some('e') | Some('E')) {
+        is_float = true;
+        text.push('e');
+        self.take().unwrap();
+        if matches!(self.peek(0), Some('+') | Some('-')) {
+          text.push(self.take().unwrap());
+        }
+        if scan_digits!(text) == 0 {
+          err!(LexErrorKind::MalformedInteger, start_pos, "Malformed float: exponent has no digits")
         }
       }
 
-      self.tokens.push(Token {
-        token: TokenType::IntegerLiteral(value),
-        position: start_pos,
-      });
+      if is_float {
+        let value = match text.parse::<f64>() {
+          Ok(x) => x,
+          Err(_) => err!(LexErrorKind::MalformedInteger, start_pos, "Malformed float literal"),
+        };
+        self.tokens.push(Token {
+          token: TokenType::FloatLiteral(value),
+          position: start_pos,
+        });
+      } else {
+        let value = match text.parse::<isize>() {
+          Ok(x) => x,
+          Err(_) => err!(LexErrorKind::MalformedInteger, start_pos, "Malformed integer: value out of range"),
+        };
+        self.tokens.push(Token {
+          token: TokenType::IntegerLiteral(value),
+          position: start_pos,
+        });
+      }
       return Ok(false);
     }
 
@@ -208,14 +418,10 @@ impl<'a> Tokenizer<'a> {
       loop {
         match self.take() {
           Some('\\') => {
-            match self.take() {
-              //TODO more escape seq and hex escape
-              Some('n') => str.push('\n'),
-              Some('r') => str.push('\r'),
-              Some('"') => str.push('"'),
-              Some(x) => err!(format!("Invalid escape sequence: \\{}", x)),
-              None => err!("Malformed escape sequence: EOF reached")
-            };
+            match self.decode_escape() {
+              Ok(chr) => str.push(chr),
+              Err((kind, message)) => err!(kind, start_pos, message),
+            }
           }
           Some('"') => {
             break
@@ -223,7 +429,7 @@ impl<'a> Tokenizer<'a> {
           Some(x) => {
             str.push(x);
           }
-          None => err!(format!("Unterminated string (starts on line {}, column {})", start_pos.row + 1, start_pos.col + 1))
+          None => err!(LexErrorKind::UnterminatedString, start_pos, format!("Unterminated string (starts on line {}, column {})", start_pos.row + 1, start_pos.col + 1))
         }
       }
 
@@ -235,6 +441,87 @@ impl<'a> Tokenizer<'a> {
     }
 
 
+    // CHAR TOKEN
+
+
+    if chr == '\'' {
+      self.take().unwrap();
+      let value = match self.take() {
+        Some('\\') => match self.decode_escape() {
+          Ok(chr) => chr,
+          Err((kind, message)) => err!(kind, start_pos, message),
+        },
+        Some('\'') => err!(LexErrorKind::MalformedChar, start_pos, "Empty character literal"),
+        Some(x) => x,
+        None => err!(LexErrorKind::MalformedChar, start_pos, format!("Unterminated character literal (starts on line {}, column {})", start_pos.row + 1, start_pos.col + 1)),
+      };
+      match self.take() {
+        Some('\'') => {}
+        Some(_) => err!(LexErrorKind::MalformedChar, start_pos, "Character literal may only contain one character"),
+        None => err!(LexErrorKind::MalformedChar, start_pos, format!("Unterminated character literal (starts on line {}, column {})", start_pos.row + 1, start_pos.col + 1)),
+      }
+
+      self.tokens.push(Token {
+        token: TokenType::CharLiteral(value),
+        position: start_pos,
+      });
+      return Ok(false);
+    }
+
+
+    // COMMENT TOKEN
+
+
+    if chr == ';' || (chr == '/' && matches!(self.peek(1), Some('/') | Some('*'))) {
+      // A block comment opens with `/*`; everything else here is a line comment
+      if chr == '/' && self.peek(1) == Some('*') {
+        self.take().unwrap(); // '/'
+        self.take().unwrap(); // '*'
+        let mut text = String::new();
+        let mut depth = 1usize;
+        loop {
+          match self.peek(0) {
+            Some('/') if self.peek(1) == Some('*') => {
+              self.take().unwrap();
+              self.take().unwrap();
+              depth += 1;
+              text.push_str("/*");
+            }
+            Some('*') if self.peek(1) == Some('/') => {
+              self.take().unwrap();
+              self.take().unwrap();
+              depth -= 1;
+              if depth == 0 { break }
+              text.push_str("*/");
+            }
+            // `take` routes through `next_auto`, so newlines advance the row
+            Some(_) => { text.push(self.take().unwrap()); }
+            None => err!(LexErrorKind::UnterminatedBlockComment, start_pos, format!("Unterminated block comment (starts on line {}, column {})", start_pos.row + 1, start_pos.col + 1)),
+          }
+        }
+        self.tokens.push(Token {
+          token: TokenType::Comment { shape: CommentShape::Block, text },
+          position: start_pos,
+        });
+        return Ok(false);
+      }
+
+      // Line comment: skip the `//` or `;` marker, then read to end of line
+      self.take().unwrap();
+      if chr == '/' { self.take().unwrap(); }
+      let mut text = String::new();
+      while let Some(x) = self.peek(0) {
+        if x == '\n' { break }
+        text.push(self.take().unwrap());
+      }
+      self.tokens.push(Token {
+        token: TokenType::Comment { shape: CommentShape::Line, text },
+        position: start_pos,
+      });
+      return Ok(false);
+    }
+
+
     // INSTR TOKEN
 
     if chr.is_alphabetic() {
@@ -283,10 +570,11 @@ impl<'a> Tokenizer<'a> {
           token: TokenType::InstructionOrKeyword(word),
           position: start_pos,
         }
-      })
+      });
+      return Ok(false);
     }
 
-    err!("Invalid token: No token matched");
+    err!(LexErrorKind::UnexpectedChar, start_pos, "Invalid token: No token matched");
   }
 
   /// Run tokenizer until the end of file (EOF)
@@ -301,3 +589,93 @@ impl<'a> Tokenizer<'a> {
     self.tokens
   }
 }
+
+impl<'a> IntoIterator for Tokenizer<'a> {
+  type Item = Result<Token>;
+  type IntoIter = TokenIterator<'a>;
+  /// Turn the tokenizer into a lazy stream of tokens driven by [`Tokenizer::step`].
+  fn into_iter(mut self) -> TokenIterator<'a> {
+    let ready = self.tokens.drain(..).collect();
+    TokenIterator {
+      tokenizer: self,
+      ready,
+      lookahead: VecDeque::new(),
+      finished: false,
+    }
+  }
+}
+
+/// A lazy [`Iterator`] over a source's tokens.
+///
+/// Produced by `Tokenizer::into_iter`, it yields one [`Token`] per `next()` by
+/// calling [`Tokenizer::step`] under the hood and stops right after emitting a
+/// [`TokenType::Eof`] token, so consumers never buffer the whole file. Parsers
+/// that need lookahead can wrap it in [`std::iter::Peekable`] or use the
+/// [`peek_token`](TokenIterator::peek_token)/[`peek2`](TokenIterator::peek2) helpers.
+pub struct TokenIterator<'a> {
+  tokenizer: Tokenizer<'a>,
+  /// Tokens already lexed by a single `step` but not yet yielded
+  ready: VecDeque<Token>,
+  /// Items produced ahead of time to satisfy `peek_token`/`peek2`
+  lookahead: VecDeque<Result<Token>>,
+  /// Set once [`TokenType::Eof`] or an error has been emitted
+  finished: bool,
+}
+impl<'a> TokenIterator<'a> {
+  /// Produce the next item straight from the underlying tokenizer.
+  fn produce(&mut self) -> Option<Result<Token>> {
+    if self.finished {
+      return None;
+    }
+    loop {
+      if let Some(token) = self.ready.pop_front() {
+        return Some(Ok(token));
+      }
+      match self.tokenizer.step() {
+        Ok(true) => {
+          self.finished = true;
+          return Some(Ok(Token {
+            token: TokenType::Eof,
+            position: self.tokenizer.position,
+          }));
+        }
+        Ok(false) => self.ready.extend(self.tokenizer.tokens.drain(..)),
+        Err(err) => {
+          self.finished = true;
+          return Some(Err(err));
+        }
+      }
+    }
+  }
+
+  /// Ensure at least `n` items are buffered for peeking (fewer if the stream ends).
+  fn fill(&mut self, n: usize) {
+    while self.lookahead.len() < n {
+      match self.produce() {
+        Some(item) => self.lookahead.push_back(item),
+        None => break,
+      }
+    }
+  }
+
+  /// Look at the next item without consuming it.
+  pub fn peek_token(&mut self) -> Option<&Result<Token>> {
+    self.fill(1);
+    self.lookahead.front()
+  }
+
+  /// Look at the item after next without consuming anything.
+  pub fn peek2(&mut self) -> Option<&Result<Token>> {
+    self.fill(2);
+    self.lookahead.get(1)
+  }
+}
+impl<'a> Iterator for TokenIterator<'a> {
+  type Item = Result<Token>;
+  fn next(&mut self) -> Option<Self::Item> {
+    if let Some(item) = self.lookahead.pop_front() {
+      return Some(item);
+    }
+    self.produce()
+  }
+}